@@ -0,0 +1,270 @@
+//! Errors returned by the contract, every error has a stable numeric code
+
+use near_sdk::{env, serde_json::json, FunctionError};
+
+use crate::ledger::LedgerError;
+
+// codes are grouped by area and never reused, new errors get the next free code in their group
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ContractError {
+    // trips and members
+    TripNotFound,
+    TripIdExists,
+    TripNameRequired,
+    CallerNotMember,
+    AccountNotMember,
+    LenderNotMember,
+    ParticipantNotMember,
+    AccountHasNoTrips,
+    NoMembersProvided,
+    NoOtherMember,
+    MemberHasBalance,
+    OwnerCannotLeave,
+    TripWithoutMembers,
+    TripNameTooLong,
+    // roles
+    NotTripOwner,
+    NotTripAdmin,
+    NotPartOfTrip,
+    AlreadyTripAdmin,
+    NotTripAdminAccount,
+    MemberCannotBeObserver,
+    NotTripObserver,
+    AlreadyTripOwner,
+    // invitations
+    NoPendingInvitation,
+    InviterAlreadyBlocked,
+    InviterNotBlocked,
+    // expenses
+    ExpenseNameRequired,
+    TripHasNoExpenses,
+    ExpenseNotFound,
+    ExpenseIdExists,
+    NotExpenseLender,
+    NoParticipants,
+    DuplicateParticipant,
+    LenderOnlyParticipant,
+    SplitValueRequired,
+    SplitValuesOverflow,
+    ExactAmountsMismatch,
+    PercentagesMismatch,
+    ZeroWeight,
+    LoanAmountTooLarge,
+    ExpenseNameTooLong,
+    // settlements
+    SameLenderAndOwer,
+    NothingOwed,
+    NoDebtsToNet,
+    SettlementNotFound,
+    SettlementIdExists,
+    InvalidSettlementMsg,
+    PendingSettlements,
+    // trip lifecycle
+    TripNotOpen,
+    TripNotClosed,
+    TripArchived,
+    ReopenAlreadyApproved,
+    ArchiveRequiresClosed,
+    UnsettledBalances,
+    // fungible tokens
+    TokenAlreadyAccepted,
+    TokenNotAccepted,
+    // storage
+    InsufficientDeposit,
+    AccountNotRegistered,
+    StorageInUse,
+    WithdrawExceedsAvailable,
+    RequiresOneYocto,
+    ForceUnregisterUnsupported,
+    // contract administration
+    UpgradeCodeMissing,
+    NotContractOwner,
+    ContractPaused,
+    MigrationPending,
+    TripOwnerAlreadySet,
+}
+
+impl ContractError {
+    // stable code of the error, frontends should match on this instead of the message
+    pub fn code(&self) -> u32 {
+        use ContractError::*;
+        match self {
+            TripNotFound => 1001,
+            TripIdExists => 1002,
+            TripNameRequired => 1003,
+            CallerNotMember => 1004,
+            AccountNotMember => 1005,
+            LenderNotMember => 1006,
+            ParticipantNotMember => 1007,
+            AccountHasNoTrips => 1008,
+            NoMembersProvided => 1009,
+            NoOtherMember => 1010,
+            MemberHasBalance => 1011,
+            OwnerCannotLeave => 1012,
+            TripWithoutMembers => 1013,
+            TripNameTooLong => 1014,
+            NotTripOwner => 2001,
+            NotTripAdmin => 2002,
+            NotPartOfTrip => 2003,
+            AlreadyTripAdmin => 2004,
+            NotTripAdminAccount => 2005,
+            MemberCannotBeObserver => 2006,
+            NotTripObserver => 2007,
+            AlreadyTripOwner => 2008,
+            NoPendingInvitation => 3001,
+            InviterAlreadyBlocked => 3002,
+            InviterNotBlocked => 3003,
+            ExpenseNameRequired => 4001,
+            TripHasNoExpenses => 4002,
+            ExpenseNotFound => 4003,
+            ExpenseIdExists => 4004,
+            NotExpenseLender => 4005,
+            NoParticipants => 4006,
+            DuplicateParticipant => 4007,
+            LenderOnlyParticipant => 4008,
+            SplitValueRequired => 4009,
+            SplitValuesOverflow => 4010,
+            ExactAmountsMismatch => 4011,
+            PercentagesMismatch => 4012,
+            ZeroWeight => 4013,
+            LoanAmountTooLarge => 4014,
+            ExpenseNameTooLong => 4015,
+            SameLenderAndOwer => 5001,
+            NothingOwed => 5002,
+            NoDebtsToNet => 5003,
+            SettlementNotFound => 5004,
+            SettlementIdExists => 5005,
+            InvalidSettlementMsg => 5006,
+            PendingSettlements => 5007,
+            TripNotOpen => 6001,
+            TripNotClosed => 6002,
+            TripArchived => 6003,
+            ReopenAlreadyApproved => 6004,
+            ArchiveRequiresClosed => 6005,
+            UnsettledBalances => 6006,
+            TokenAlreadyAccepted => 7001,
+            TokenNotAccepted => 7002,
+            InsufficientDeposit => 8001,
+            AccountNotRegistered => 8002,
+            StorageInUse => 8003,
+            WithdrawExceedsAvailable => 8004,
+            RequiresOneYocto => 8005,
+            ForceUnregisterUnsupported => 8006,
+            UpgradeCodeMissing => 9001,
+            NotContractOwner => 9002,
+            ContractPaused => 9003,
+            MigrationPending => 9004,
+            TripOwnerAlreadySet => 9005,
+        }
+    }
+
+    // human readable message, may change between versions
+    pub fn message(&self) -> &'static str {
+        use ContractError::*;
+        match self {
+            TripNotFound => "trip_id doesn't exist",
+            TripIdExists => "trip_id alredy exits",
+            TripNameRequired => "trip title is required",
+            CallerNotMember => "caller id not an existing trip member",
+            AccountNotMember => "account id not an existing trip member",
+            LenderNotMember => "lender not an existing trip member",
+            ParticipantNotMember => "ower not an existing trip member",
+            AccountHasNoTrips => "account_id doesn't have any trips",
+            NoMembersProvided => "no member ids provided by caller",
+            NoOtherMember => "no other account present in trip",
+            MemberHasBalance => "member has a non-zero balance in trip",
+            OwnerCannotLeave => "trip owner cannot leave, transfer ownership first",
+            TripWithoutMembers => "trip must have at least one member",
+            TripNameTooLong => "trip title is too long",
+            NotTripOwner => "caller is not the trip owner",
+            NotTripAdmin => "caller is not a trip admin",
+            NotPartOfTrip => "caller is not part of trip",
+            AlreadyTripAdmin => "account is already a trip admin",
+            NotTripAdminAccount => "account is not a trip admin",
+            MemberCannotBeObserver => "trip member cannot be an observer",
+            NotTripObserver => "account is not a trip observer",
+            AlreadyTripOwner => "account is already the trip owner",
+            NoPendingInvitation => "no pending invitation to trip",
+            InviterAlreadyBlocked => "inviter already blocked",
+            InviterNotBlocked => "inviter not blocked",
+            ExpenseNameRequired => "expense title is required",
+            TripHasNoExpenses => "trip doesn't have any expenses",
+            ExpenseNotFound => "expense_id doesn't exist in trip",
+            ExpenseIdExists => "expense_id alredy exits",
+            NotExpenseLender => "caller is not the expense lender",
+            NoParticipants => "no participants provided for expense",
+            DuplicateParticipant => "participant added more than once",
+            LenderOnlyParticipant => "lender cannot be the only participant",
+            SplitValueRequired => "split_value is required for every participant",
+            SplitValuesOverflow => "split values overflow",
+            ExactAmountsMismatch => "exact amounts must add up to loan amount",
+            PercentagesMismatch => "percentages must add up to 10000 basis points",
+            ZeroWeight => "weights must be greater than zero",
+            LoanAmountTooLarge => "loan amount too large to split",
+            ExpenseNameTooLong => "expense title is too long",
+            SameLenderAndOwer => "lender and ower cannot be same",
+            NothingOwed => "caller doesn't owe anything to lender",
+            NoDebtsToNet => "no debts to net across trips",
+            SettlementNotFound => "settlement_id doesn't exist in trip",
+            SettlementIdExists => "settlement_id alredy exits",
+            InvalidSettlementMsg => "msg is not a valid settlement message",
+            PendingSettlements => "trip has token settlements that are not resolved yet",
+            TripNotOpen => "trip is not open",
+            TripNotClosed => "trip is not closed",
+            TripArchived => "archived trip cannot be reopened",
+            ReopenAlreadyApproved => "caller already approved reopening trip",
+            ArchiveRequiresClosed => "only a closed trip can be archived",
+            UnsettledBalances => "trip has unsettled balances",
+            TokenAlreadyAccepted => "token already accepted in trip",
+            TokenNotAccepted => "token not accepted in trip",
+            InsufficientDeposit => "storage balance doesn't cover storage",
+            AccountNotRegistered => "account is not registered for storage",
+            StorageInUse => "account still uses storage, leave its trips first",
+            WithdrawExceedsAvailable => "amount exceeds available storage balance",
+            RequiresOneYocto => "requires attached deposit of exactly 1 yoctoNEAR",
+            ForceUnregisterUnsupported => "force unregister is not supported, leave trips first",
+            UpgradeCodeMissing => "no contract code provided for upgrade",
+            NotContractOwner => "caller is not the contract owner",
+            ContractPaused => "contract is paused",
+            MigrationPending => "state migration is pending",
+            TripOwnerAlreadySet => "trip already has an owner",
+        }
+    }
+
+    // json payload a failed call panics with, clients parse code and show message
+    pub fn payload(&self) -> String {
+        json!({
+            "code": self.code(),
+            "error": format!("{:?}", self),
+            "message": self.message(),
+        })
+        .to_string()
+    }
+}
+
+// panics with a json payload, eg. {"code":1001,"error":"TripNotFound","message":"trip_id doesn't exist"}
+impl FunctionError for ContractError {
+    fn panic(&self) -> ! {
+        env::panic_str(&self.payload())
+    }
+}
+
+// ledger errors keep their contract code
+impl From<LedgerError> for ContractError {
+    fn from(err: LedgerError) -> Self {
+        match err {
+            LedgerError::ParticipantNotMember => ContractError::ParticipantNotMember,
+            LedgerError::NoParticipants => ContractError::NoParticipants,
+            LedgerError::DuplicateParticipant => ContractError::DuplicateParticipant,
+            LedgerError::LenderOnlyParticipant => ContractError::LenderOnlyParticipant,
+            LedgerError::SplitValueRequired => ContractError::SplitValueRequired,
+            LedgerError::SplitValuesOverflow => ContractError::SplitValuesOverflow,
+            LedgerError::ExactAmountsMismatch => ContractError::ExactAmountsMismatch,
+            LedgerError::PercentagesMismatch => ContractError::PercentagesMismatch,
+            LedgerError::ZeroWeight => ContractError::ZeroWeight,
+            LedgerError::LoanAmountTooLarge => ContractError::LoanAmountTooLarge,
+            LedgerError::SameLenderAndOwer => ContractError::SameLenderAndOwer,
+            LedgerError::NothingOwed => ContractError::NothingOwed,
+        }
+    }
+}
@@ -0,0 +1,401 @@
+//! NEP-297 events emitted by the contract, logged as `EVENT_JSON:{...}`
+
+use near_sdk::json_types::U128;
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{env, serde_json, AccountId};
+
+use crate::{Expense, ExpenseId, ParticipantShare, Settlement, SettlementId, SettlementKind, SplitMode, StoragePolicy, TripId, TripStatus};
+
+pub const EVENT_STANDARD: &str = "expense_tracker";
+pub const EVENT_STANDARD_VERSION: &str = "1.0.0";
+
+// events of the contract, every event carries a list of data entries
+#[derive(Serialize, Deserialize)]
+#[derive(Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+#[serde(tag = "event", content = "data")]
+#[serde(rename_all = "snake_case")]
+pub enum ContractEvent {
+    TripCreated(Vec<TripCreatedData>),
+    MembersAdded(Vec<MembersAddedData>),
+    MembersRemoved(Vec<MembersRemovedData>),
+    TripDeleted(Vec<TripDeletedData>),
+    ExpenseAdded(Vec<ExpenseData>),
+    ExpenseUpdated(Vec<ExpenseData>),
+    ExpenseDeleted(Vec<ExpenseDeletedData>),
+    Settlement(Vec<SettlementData>),
+    SettlementReverted(Vec<SettlementRevertedData>),
+    MembersInvited(Vec<MembersInvitedData>),
+    InvitationDeclined(Vec<InvitationDeclinedData>),
+    InviterBlocked(Vec<InviterData>),
+    InviterUnblocked(Vec<InviterData>),
+    TripRenamed(Vec<TripRenamedData>),
+    AdminAdded(Vec<AdminData>),
+    AdminRemoved(Vec<AdminData>),
+    ObserversAdded(Vec<ObserversData>),
+    ObserversRemoved(Vec<ObserversData>),
+    TripOwnershipTransferred(Vec<TripOwnershipData>),
+    TripStatusChanged(Vec<TripStatusData>),
+    ReopenApproved(Vec<ReopenApprovedData>),
+    AcceptedTokenAdded(Vec<AcceptedTokenData>),
+    AcceptedTokenRemoved(Vec<AcceptedTokenData>),
+    StoragePolicySet(Vec<StoragePolicyData>),
+    TripStorageDeposited(Vec<TripStorageDepositData>),
+    TripStorageOptInSet(Vec<TripStorageOptInData>),
+    ContractMigrated(Vec<ContractMigratedData>),
+    ContractUpgraded(Vec<ContractUpgradedData>),
+    ContractOwnershipTransferred(Vec<ContractOwnershipData>),
+    ContractPauseSet(Vec<ContractPauseData>),
+    ScratchMapsCleared(Vec<ScratchMapsClearedData>),
+    MigrationProgress(Vec<MigrationProgressData>),
+    LegacyTripOwnerSet(Vec<LegacyTripOwnerData>),
+    StorageDeposited(Vec<StorageAmountData>),
+    StorageWithdrawn(Vec<StorageAmountData>),
+    StorageUnregistered(Vec<StorageAmountData>),
+}
+
+// trip was created, owner is its only member until invitees accept
+#[derive(Serialize, Deserialize)]
+#[derive(Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TripCreatedData {
+    pub trip_id: TripId,
+    pub trip_name: Option<String>,
+    pub owner_id: AccountId,
+    pub invitee_ids: Vec<AccountId>,
+}
+
+// accounts joined a trip as members
+#[derive(Serialize, Deserialize)]
+#[derive(Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct MembersAddedData {
+    pub trip_id: TripId,
+    pub member_ids: Vec<AccountId>,
+}
+
+// members left or were removed from a trip
+#[derive(Serialize, Deserialize)]
+#[derive(Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct MembersRemovedData {
+    pub trip_id: TripId,
+    pub member_ids: Vec<AccountId>,
+}
+
+// trip was deleted together with its expenses and settlements
+#[derive(Serialize, Deserialize)]
+#[derive(Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TripDeletedData {
+    pub trip_id: TripId,
+}
+
+// expense as added or updated
+#[derive(Serialize, Deserialize)]
+#[derive(Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ExpenseData {
+    pub trip_id: TripId,
+    pub expense_id: ExpenseId,
+    pub expense_name: Option<String>,
+    pub lender_id: AccountId,
+    pub loan_amount: U128,
+    pub split_mode: SplitMode,
+    pub participant_shares: Vec<ParticipantShare>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[derive(Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ExpenseDeletedData {
+    pub trip_id: TripId,
+    pub expense_id: ExpenseId,
+}
+
+// ower settled amount with lender, offsets move no funds
+#[derive(Serialize, Deserialize)]
+#[derive(Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct SettlementData {
+    pub trip_id: TripId,
+    pub settlement_id: SettlementId,
+    pub ower_id: AccountId,
+    pub lender_id: AccountId,
+    pub amount: U128,
+    // fungible token contract, none for NEAR
+    pub token_id: Option<AccountId>,
+    pub kind: SettlementKind,
+}
+
+// token transfer of a settlement failed, settlement no longer counts
+#[derive(Serialize, Deserialize)]
+#[derive(Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct SettlementRevertedData {
+    pub trip_id: TripId,
+    pub settlement_id: SettlementId,
+}
+
+// accounts were invited to a trip, they join once they accept
+#[derive(Serialize, Deserialize)]
+#[derive(Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct MembersInvitedData {
+    pub trip_id: TripId,
+    pub inviter_id: AccountId,
+    pub invitee_ids: Vec<AccountId>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[derive(Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct InvitationDeclinedData {
+    pub trip_id: TripId,
+    pub invitee_id: AccountId,
+    pub inviter_id: AccountId,
+}
+
+// account blocked or unblocked invitations from inviter
+#[derive(Serialize, Deserialize)]
+#[derive(Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct InviterData {
+    pub account_id: AccountId,
+    pub inviter_id: AccountId,
+}
+
+#[derive(Serialize, Deserialize)]
+#[derive(Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TripRenamedData {
+    pub trip_id: TripId,
+    pub trip_name: Option<String>,
+}
+
+// member was made or stopped being a trip admin
+#[derive(Serialize, Deserialize)]
+#[derive(Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct AdminData {
+    pub trip_id: TripId,
+    pub admin_id: AccountId,
+}
+
+// observers were added to or removed from a trip
+#[derive(Serialize, Deserialize)]
+#[derive(Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ObserversData {
+    pub trip_id: TripId,
+    pub observer_ids: Vec<AccountId>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[derive(Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TripOwnershipData {
+    pub trip_id: TripId,
+    pub old_owner_id: AccountId,
+    pub new_owner_id: AccountId,
+}
+
+// trip was closed or archived
+#[derive(Serialize, Deserialize)]
+#[derive(Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TripStatusData {
+    pub trip_id: TripId,
+    pub status: TripStatus,
+}
+
+// member approved reopening a closed trip, status is open once enough members approved
+#[derive(Serialize, Deserialize)]
+#[derive(Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ReopenApprovedData {
+    pub trip_id: TripId,
+    pub approver_id: AccountId,
+    pub status: TripStatus,
+}
+
+// fungible token settlements of a trip accept
+#[derive(Serialize, Deserialize)]
+#[derive(Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct AcceptedTokenData {
+    pub trip_id: TripId,
+    pub token_id: AccountId,
+}
+
+#[derive(Serialize, Deserialize)]
+#[derive(Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct StoragePolicyData {
+    pub trip_id: TripId,
+    pub policy: StoragePolicy,
+}
+
+// account topped up the storage pool of a trip
+#[derive(Serialize, Deserialize)]
+#[derive(Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TripStorageDepositData {
+    pub trip_id: TripId,
+    pub account_id: AccountId,
+    pub amount: U128,
+}
+
+// member agreed to or stopped paying its share of trip storage by policy
+#[derive(Serialize, Deserialize)]
+#[derive(Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TripStorageOptInData {
+    pub trip_id: TripId,
+    pub account_id: AccountId,
+    pub opt_in: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+#[derive(Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ContractMigratedData {
+    pub from_version: u32,
+    pub to_version: u32,
+}
+
+// new contract code was deployed, code hash is base58
+#[derive(Serialize, Deserialize)]
+#[derive(Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ContractUpgradedData {
+    pub code_hash: String,
+}
+
+#[derive(Serialize, Deserialize)]
+#[derive(Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ContractOwnershipData {
+    pub old_owner_id: AccountId,
+    pub new_owner_id: AccountId,
+}
+
+#[derive(Serialize, Deserialize)]
+#[derive(Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ContractPauseData {
+    pub paused: bool,
+}
+
+// leaked summary scratch maps were cleared from storage keys from_key up to next_key
+#[derive(Serialize, Deserialize)]
+#[derive(Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ScratchMapsClearedData {
+    pub from_key: u32,
+    pub next_key: Option<u32>,
+    pub removed_entries: u64,
+    pub released_storage: u64,
+}
+
+// step of the migration from v1.0 that trips are processed in
+#[derive(Serialize, Deserialize)]
+#[derive(Clone, Copy, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+#[serde(rename_all = "snake_case")]
+pub enum MigrationStep {
+    LegacyRecords,
+    TripMembers,
+    TripLedgers,
+}
+
+// trips from_index up to next_index went through a migration step, next index is none once the step is done
+#[derive(Serialize, Deserialize)]
+#[derive(Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct MigrationProgressData {
+    pub step: MigrationStep,
+    pub from_index: u64,
+    pub next_index: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[derive(Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct LegacyTripOwnerData {
+    pub trip_id: TripId,
+    pub owner_id: AccountId,
+}
+
+// amount added to, withdrawn from or refunded with the storage balance of an account
+#[derive(Serialize, Deserialize)]
+#[derive(Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct StorageAmountData {
+    pub account_id: AccountId,
+    pub amount: U128,
+}
+
+impl ExpenseData {
+    pub fn new(trip_id: &TripId, expense: &Expense) -> Self {
+        Self {
+            trip_id: trip_id.clone(),
+            expense_id: expense.expense_id.clone().unwrap_or_default(),
+            expense_name: expense.expense_name.clone(),
+            lender_id: expense.lender_id.clone(),
+            loan_amount: expense.loan_amount,
+            split_mode: expense.split_mode,
+            participant_shares: expense.participant_shares.clone(),
+        }
+    }
+}
+
+impl SettlementData {
+    pub fn new(trip_id: &TripId, settlement: &Settlement) -> Self {
+        Self {
+            trip_id: trip_id.clone(),
+            settlement_id: settlement.settlement_id.clone().unwrap_or_default(),
+            ower_id: settlement.ower_id.clone(),
+            lender_id: settlement.lender_id.clone(),
+            amount: settlement.amount,
+            token_id: settlement.token_id.clone(),
+            kind: settlement.kind,
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+struct EventLog<'a> {
+    standard: &'static str,
+    version: &'static str,
+    #[serde(flatten)]
+    event: &'a ContractEvent,
+}
+
+#[derive(Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+struct ParsedEventLog {
+    standard: String,
+    #[serde(flatten)]
+    event: ContractEvent,
+}
+
+impl ContractEvent {
+    // log event in NEP-297 format
+    pub fn emit(&self) {
+        let event_log = EventLog {
+            standard: EVENT_STANDARD,
+            version: EVENT_STANDARD_VERSION,
+            event: self,
+        };
+        env::log_str(&format!("EVENT_JSON:{}", serde_json::to_string(&event_log).unwrap()));
+    }
+
+    // parse a log line emitted by this contract, none for other logs
+    pub fn from_log(log: &str) -> Option<Self> {
+        let json = log.strip_prefix("EVENT_JSON:")?;
+        let event_log: ParsedEventLog = serde_json::from_str(json).ok()?;
+        (event_log.standard == EVENT_STANDARD).then_some(event_log.event)
+    }
+}
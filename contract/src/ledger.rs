@@ -0,0 +1,337 @@
+//! Trip ledger core: splits, balances and debt simplification in plain Rust
+//!
+//! Nothing here reads the blockchain, accounts are any ordered id type and balances live in any
+//! `BalanceStore`, so the contract, the event replay and backend services share the same rules
+
+use std::collections::BTreeMap;
+
+// percentages are in basis points
+pub const PERCENTAGE_TOTAL: u128 = 10000;
+
+// how an expense amount is split between participants
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SplitMode {
+    // every participant owes the same amount
+    Equal,
+    // split value is the exact amount a participant owes
+    Exact,
+    // split value is in basis points of the amount
+    Percentage,
+    // participants owe in proportion to their split value
+    Weighted,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum LedgerError {
+    ParticipantNotMember,
+    NoParticipants,
+    DuplicateParticipant,
+    LenderOnlyParticipant,
+    SplitValueRequired,
+    SplitValuesOverflow,
+    ExactAmountsMismatch,
+    PercentagesMismatch,
+    ZeroWeight,
+    LoanAmountTooLarge,
+    SameLenderAndOwer,
+    NothingOwed,
+}
+
+// return the error from the enclosing function unless the condition holds
+macro_rules! ensure {
+    ($cond:expr, $err:expr $(,)?) => {
+        if !$cond {
+            return Err($err);
+        }
+    };
+}
+pub(crate) use ensure;
+
+// check participants of an expense are unique members and someone other than lender owes
+pub fn check_participants<A: PartialEq>(
+    lender_id: &A,
+    participant_ids: &[&A],
+    is_member: impl Fn(&A) -> bool,
+) -> Result<(), LedgerError> {
+    ensure!(!participant_ids.is_empty(), LedgerError::NoParticipants);
+
+    for (i, participant_id) in participant_ids.iter().enumerate() {
+        ensure!(is_member(participant_id), LedgerError::ParticipantNotMember);
+        ensure!(!participant_ids[..i].contains(participant_id), LedgerError::DuplicateParticipant);
+    }
+
+    // check lender is not the only ower
+    ensure!(participant_ids.iter().any(|p| *p != lender_id), LedgerError::LenderOnlyParticipant);
+    Ok(())
+}
+
+// split amount between participants by their split values, any rounding remainder goes one unit each to the first participants
+// split values are ignored by an equal split and required otherwise
+pub fn split_amount<A: Clone>(
+    amount: u128,
+    split_mode: SplitMode,
+    participants: &[(A, Option<u128>)],
+) -> Result<Vec<(A, u128)>, LedgerError> {
+    let split_values: Vec<u128> = match split_mode {
+        SplitMode::Equal => vec![1; participants.len()],
+        _ => participants
+            .iter()
+            .map(|(_, v)| v.ok_or(LedgerError::SplitValueRequired))
+            .collect::<Result<_, _>>()?,
+    };
+    let split_total = split_values.iter().try_fold(0u128, |sum, v| sum.checked_add(*v)).ok_or(LedgerError::SplitValuesOverflow)?;
+
+    // check split values against split mode
+    match split_mode {
+        SplitMode::Exact => ensure!(split_total == amount, LedgerError::ExactAmountsMismatch),
+        SplitMode::Percentage => ensure!(split_total == PERCENTAGE_TOTAL, LedgerError::PercentagesMismatch),
+        SplitMode::Weighted => ensure!(split_values.iter().all(|v| *v > 0), LedgerError::ZeroWeight),
+        SplitMode::Equal => {},
+    }
+
+    // amounts are already exact
+    if split_mode == SplitMode::Exact {
+        return Ok(participants.iter().map(|(a, _)| a.clone()).zip(split_values).collect());
+    }
+
+    // proportional amounts rounded down
+    let mut share_amounts: Vec<u128> = split_values
+        .iter()
+        .map(|v| amount.checked_mul(*v).map(|a| a / split_total).ok_or(LedgerError::LoanAmountTooLarge))
+        .collect::<Result<_, _>>()?;
+
+    // hand out remainder so shares add up to amount
+    let remainder = amount - share_amounts.iter().sum::<u128>();
+    for share_amount in share_amounts.iter_mut().take(remainder as usize) {
+        *share_amount += 1;
+    }
+
+    Ok(participants.iter().map(|(a, _)| a.clone()).zip(share_amounts).collect())
+}
+
+// where balances of a trip are kept, net balance is positive if account is owed and
+// a pair is stored once in account id order, positive if second account owes first
+pub trait BalanceStore<A> {
+    fn net_balance(&self, account_id: &A) -> i128;
+    fn set_net_balance(&mut self, account_id: &A, balance: i128);
+    fn stored_pair_balance(&self, pair: &(A, A)) -> i128;
+    fn set_stored_pair_balance(&mut self, pair: &(A, A), balance: i128);
+}
+
+// add amount ower owes lender, a negative amount reduces the debt
+pub fn add_debt<A: Ord + Clone>(
+    store: &mut impl BalanceStore<A>,
+    lender_id: &A,
+    ower_id: &A,
+    amount: i128,
+) {
+    if amount == 0 || lender_id == ower_id {
+        return;
+    }
+
+    // lender is owed more, ower owes more
+    for (account_id, change) in [(lender_id, amount), (ower_id, -amount)] {
+        let net_balance = store.net_balance(account_id) + change;
+        store.set_net_balance(account_id, net_balance);
+    }
+
+    let (pair, change) = if lender_id < ower_id {
+        ((lender_id.clone(), ower_id.clone()), amount)
+    } else {
+        ((ower_id.clone(), lender_id.clone()), -amount)
+    };
+    let pair_balance = store.stored_pair_balance(&pair) + change;
+    store.set_stored_pair_balance(&pair, pair_balance);
+}
+
+// add shares participants owe lender, sign -1 reverses them
+pub fn apply_shares<A: Ord + Clone>(
+    store: &mut impl BalanceStore<A>,
+    lender_id: &A,
+    shares: &[(A, u128)],
+    sign: i128,
+) {
+    for (account_id, share_amount) in shares {
+        add_debt(store, lender_id, account_id, sign * *share_amount as i128);
+    }
+}
+
+// balance between two accounts, positive if other account owes account
+pub fn pair_balance<A: Ord + Clone>(
+    store: &impl BalanceStore<A>,
+    account_id: &A,
+    other_id: &A,
+) -> i128 {
+    if account_id < other_id {
+        store.stored_pair_balance(&(account_id.clone(), other_id.clone()))
+    } else {
+        -store.stored_pair_balance(&(other_id.clone(), account_id.clone()))
+    }
+}
+
+// check ower owes lender, return outstanding amount
+pub fn outstanding_debt<A: Ord + Clone>(
+    store: &impl BalanceStore<A>,
+    lender_id: &A,
+    ower_id: &A,
+) -> Result<u128, LedgerError> {
+    ensure!(lender_id != ower_id, LedgerError::SameLenderAndOwer);
+
+    let outstanding_amount = pair_balance(store, lender_id, ower_id);
+    ensure!(outstanding_amount > 0, LedgerError::NothingOwed);
+
+    Ok(outstanding_amount.unsigned_abs())
+}
+
+// balances kept in memory
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Balances<A: Ord> {
+    net_balances: BTreeMap<A, i128>,
+    pair_balances: BTreeMap<(A, A), i128>,
+}
+
+impl<A: Ord> Default for Balances<A> {
+    fn default() -> Self {
+        Self { net_balances: BTreeMap::new(), pair_balances: BTreeMap::new() }
+    }
+}
+
+impl<A: Ord + Clone> Balances<A> {
+    // every non-zero net balance
+    pub fn net_balances(&self) -> BTreeMap<A, i128> {
+        self.net_balances.iter().filter(|(_, b)| **b != 0).map(|(a, b)| (a.clone(), *b)).collect()
+    }
+
+    // balance between two accounts, positive if other account owes account
+    pub fn pair_balance(&self, account_id: &A, other_id: &A) -> i128 {
+        pair_balance(self, account_id, other_id)
+    }
+}
+
+impl<A: Ord + Clone> BalanceStore<A> for Balances<A> {
+    fn net_balance(&self, account_id: &A) -> i128 {
+        self.net_balances.get(account_id).copied().unwrap_or(0)
+    }
+
+    fn set_net_balance(&mut self, account_id: &A, balance: i128) {
+        self.net_balances.insert(account_id.clone(), balance);
+    }
+
+    fn stored_pair_balance(&self, pair: &(A, A)) -> i128 {
+        self.pair_balances.get(pair).copied().unwrap_or(0)
+    }
+
+    fn set_stored_pair_balance(&mut self, pair: &(A, A), balance: i128) {
+        self.pair_balances.insert(pair.clone(), balance);
+    }
+}
+
+// transfer that settles part of the debts of a group
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Transfer<A> {
+    pub ower_id: A,
+    pub lender_id: A,
+    pub amount: u128,
+}
+
+// greedy debt simplification, largest ower pays largest lender until all balances are zero
+// gives at most one transfer less than the number of accounts with a balance
+pub fn simplify_debts<A: Clone>(
+    net_balances: impl IntoIterator<Item = (A, i128)>,
+) -> Vec<Transfer<A>> {
+    let mut lenders: Vec<(A, u128)> = vec![];
+    let mut owers: Vec<(A, u128)> = vec![];
+    for (account_id, net_amount) in net_balances {
+        if net_amount > 0 {
+            lenders.push((account_id, net_amount.unsigned_abs()));
+        } else if net_amount < 0 {
+            owers.push((account_id, net_amount.unsigned_abs()));
+        }
+    }
+
+    let mut transfers: Vec<Transfer<A>> = vec![];
+    // pick largest balances on both sides
+    while let (Some(lender), Some(ower)) = (largest_balance(&lenders), largest_balance(&owers)) {
+        // settle as much as both sides allow
+        let amount = lenders[lender].1.min(owers[ower].1);
+        lenders[lender].1 -= amount;
+        owers[ower].1 -= amount;
+        transfers.push(Transfer {
+            ower_id: owers[ower].0.clone(),
+            lender_id: lenders[lender].0.clone(),
+            amount,
+        });
+    }
+
+    transfers
+}
+
+// index of largest non-zero balance, first account wins a tie
+fn largest_balance<A>(balances: &[(A, u128)]) -> Option<usize> {
+    let mut largest: Option<usize> = None;
+    for (i, (_, amount)) in balances.iter().enumerate() {
+        if *amount > 0 && !matches!(largest, Some(l) if balances[l].1 >= *amount) {
+            largest = Some(i);
+        }
+    }
+    largest
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    // check simplify_debts chains through instead of pairwise transfers
+    fn test_simplify_debts() {
+        // 1 owes 2 and 2 owes 3 the same amount, so 1 pays 3 directly
+        let net_balances = BTreeMap::from([(1, -50), (2, 0), (3, 50)]);
+        assert_eq!(simplify_debts(net_balances),vec![
+            Transfer{ ower_id: 1, lender_id: 3, amount: 50 },
+        ]);
+
+        // two owers, two lenders
+        let net_balances = BTreeMap::from([(1, 70), (2, -100), (3, 30), (4, 0)]);
+        assert_eq!(simplify_debts(net_balances),vec![
+            Transfer{ ower_id: 2, lender_id: 1, amount: 70 },
+            Transfer{ ower_id: 2, lender_id: 3, amount: 30 },
+        ]);
+    }
+
+
+    #[test]
+    // check splits and balances with plain account ids and no blockchain context
+    fn test_split_and_balances() {
+        let (alice, bob, carol) = ("alice".to_string(), "bob".to_string(), "carol".to_string());
+        let is_member = |a: &String| [&alice, &bob, &carol].contains(&a);
+
+        // test 1, amounts are split by split mode, remainder goes to the first participants
+        let shares = split_amount(100, SplitMode::Equal, &[(alice.clone(), None), (bob.clone(), None), (carol.clone(), None)]).unwrap();
+        assert_eq!(shares, vec![(alice.clone(), 34), (bob.clone(), 33), (carol.clone(), 33)]);
+        assert_eq!(split_amount(60, SplitMode::Percentage, &[(carol.clone(), Some(5000)), (alice.clone(), Some(5000))]).unwrap(), vec![(carol.clone(), 30), (alice.clone(), 30)]);
+        assert_eq!(split_amount(10, SplitMode::Exact, &[(bob.clone(), Some(9))]).err(), Some(LedgerError::ExactAmountsMismatch));
+        assert_eq!(check_participants(&alice, &[&"dave".to_string()], is_member).err(), Some(LedgerError::ParticipantNotMember));
+        assert_eq!(check_participants(&alice, &[&alice], is_member).err(), Some(LedgerError::LenderOnlyParticipant));
+
+        // test 2, shares are applied and reversed
+        let mut balances = Balances::default();
+        apply_shares(&mut balances, &alice, &shares, 1);
+        apply_shares(&mut balances, &bob, &[(carol.clone(), 30), (alice.clone(), 30)], 1);
+        assert_eq!(balances.pair_balance(&alice, &bob), 3);
+        assert_eq!(balances.pair_balance(&bob, &carol), 30);
+        assert_eq!(balances.net_balances().get(&carol).copied(), Some(-63));
+        apply_shares(&mut balances, &bob, &[(carol.clone(), 30), (alice.clone(), 30)], -1);
+        assert_eq!(balances.pair_balance(&bob, &carol), 0);
+
+        // test 3, settlements and settlement plan
+        assert_eq!(outstanding_debt(&balances, &carol, &bob).err(), Some(LedgerError::NothingOwed));
+        assert_eq!(simplify_debts(balances.net_balances()), vec![
+            Transfer { ower_id: bob.clone(), lender_id: alice.clone(), amount: 33 },
+            Transfer { ower_id: carol.clone(), lender_id: alice.clone(), amount: 33 },
+        ]);
+        assert_eq!(outstanding_debt(&balances, &alice, &carol), Ok(33));
+        add_debt(&mut balances, &alice, &carol, -33);
+        assert_eq!(balances.net_balances().get(&carol), None);
+    }
+}
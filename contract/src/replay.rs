@@ -0,0 +1,175 @@
+//! Rebuilds trips, expenses and balances offline by folding the contract's NEP-297 events
+//!
+//! Enabled with the `replay` feature, eg. for indexers reading logs of `EVENT_JSON:` lines
+
+use std::collections::BTreeMap;
+
+use near_sdk::AccountId;
+
+use crate::events::{ContractEvent, ExpenseData, SettlementData};
+use crate::ledger::{self, Balances};
+use crate::{ExpenseId, SettlementId, TripId};
+
+// state of every trip seen in the replayed events
+#[derive(Default, Clone, PartialEq, Debug)]
+pub struct ReplayState {
+    trips: BTreeMap<TripId, ReplayTrip>,
+}
+
+// trip rebuilt from events, balances follow the contract ledger
+#[derive(Default, Clone, PartialEq, Debug)]
+pub struct ReplayTrip {
+    pub members: Vec<AccountId>,
+    pub expenses: BTreeMap<ExpenseId, ExpenseData>,
+    pub settlements: BTreeMap<SettlementId, SettlementData>,
+    balances: Balances<AccountId>,
+}
+
+impl ReplayState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // replay log lines in order, lines that are not events of this contract are skipped
+    pub fn replay<I, S>(logs: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let mut state = Self::new();
+        for log in logs {
+            state.apply_log(log.as_ref());
+        }
+        state
+    }
+
+    // apply a log line, returns false if it isn't an event of this contract
+    pub fn apply_log(&mut self, log: &str) -> bool {
+        match ContractEvent::from_log(log) {
+            Some(event) => {
+                self.apply(&event);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // apply an event, events of unknown trips start an empty trip
+    pub fn apply(&mut self, event: &ContractEvent) {
+        match event {
+            ContractEvent::TripCreated(data) => {
+                for d in data {
+                    self.trips.insert(d.trip_id.clone(), ReplayTrip {
+                        members: vec![d.owner_id.clone()],
+                        ..Default::default()
+                    });
+                }
+            }
+            ContractEvent::MembersAdded(data) => {
+                for d in data {
+                    let trip = self.trip_mut(&d.trip_id);
+                    for member_id in d.member_ids.iter() {
+                        if !trip.members.contains(member_id) {
+                            trip.members.push(member_id.clone());
+                        }
+                    }
+                }
+            }
+            ContractEvent::MembersRemoved(data) => {
+                for d in data {
+                    let trip = self.trip_mut(&d.trip_id);
+                    // contract member sets move the last member into a removed member's place
+                    for member_id in d.member_ids.iter() {
+                        if let Some(i) = trip.members.iter().position(|m| m == member_id) {
+                            trip.members.swap_remove(i);
+                        }
+                    }
+                }
+            }
+            ContractEvent::TripDeleted(data) => {
+                for d in data {
+                    self.trips.remove(&d.trip_id);
+                }
+            }
+            ContractEvent::ExpenseAdded(data) | ContractEvent::ExpenseUpdated(data) => {
+                for d in data {
+                    let trip = self.trip_mut(&d.trip_id);
+                    // an update replaces the previous version of the expense
+                    if let Some(old_expense) = trip.expenses.remove(&d.expense_id) {
+                        trip.apply_expense(&old_expense, -1);
+                    }
+                    trip.apply_expense(d, 1);
+                    trip.expenses.insert(d.expense_id.clone(), d.clone());
+                }
+            }
+            ContractEvent::ExpenseDeleted(data) => {
+                for d in data {
+                    let trip = self.trip_mut(&d.trip_id);
+                    if let Some(old_expense) = trip.expenses.remove(&d.expense_id) {
+                        trip.apply_expense(&old_expense, -1);
+                    }
+                }
+            }
+            ContractEvent::Settlement(data) => {
+                for d in data {
+                    let trip = self.trip_mut(&d.trip_id);
+                    trip.add_debt(&d.lender_id, &d.ower_id, -(d.amount.0 as i128));
+                    trip.settlements.insert(d.settlement_id.clone(), d.clone());
+                }
+            }
+            ContractEvent::SettlementReverted(data) => {
+                for d in data {
+                    let trip = self.trip_mut(&d.trip_id);
+                    // settlement is kept with a zero amount, as in the contract
+                    if let Some(settlement) = trip.settlements.get_mut(&d.settlement_id) {
+                        let amount = std::mem::take(&mut settlement.amount.0);
+                        let (lender_id, ower_id) = (settlement.lender_id.clone(), settlement.ower_id.clone());
+                        trip.add_debt(&lender_id, &ower_id, amount as i128);
+                    }
+                }
+            }
+            // other events don't change members, expenses or settlements
+            _ => {}
+        }
+    }
+
+    pub fn trip(&self, trip_id: &TripId) -> Option<&ReplayTrip> {
+        self.trips.get(trip_id)
+    }
+
+    pub fn trips(&self) -> &BTreeMap<TripId, ReplayTrip> {
+        &self.trips
+    }
+
+    fn trip_mut(&mut self, trip_id: &TripId) -> &mut ReplayTrip {
+        self.trips.entry(trip_id.clone()).or_default()
+    }
+}
+
+impl ReplayTrip {
+    // net balance of an account, positive if it is owed
+    pub fn net_balance(&self, account_id: &AccountId) -> i128 {
+        ledger::BalanceStore::net_balance(&self.balances, account_id)
+    }
+
+    // balance between two accounts, positive if other account owes account
+    pub fn pair_balance(&self, account_id: &AccountId, other_id: &AccountId) -> i128 {
+        self.balances.pair_balance(account_id, other_id)
+    }
+
+    // every non-zero net balance
+    pub fn net_balances(&self) -> BTreeMap<AccountId, i128> {
+        self.balances.net_balances()
+    }
+
+    // add an expense to balances, sign -1 reverses it
+    fn apply_expense(&mut self, expense: &ExpenseData, sign: i128) {
+        let shares: Vec<(AccountId, u128)> = expense.participant_shares.iter().map(|s| (s.account_id.clone(), s.share_amount.0)).collect();
+        ledger::apply_shares(&mut self.balances, &expense.lender_id, &shares, sign);
+    }
+
+    // add amount ower owes lender, a negative amount reduces the debt
+    fn add_debt(&mut self, lender_id: &AccountId, ower_id: &AccountId, amount: i128) {
+        ledger::add_debt(&mut self.balances, lender_id, ower_id, amount);
+    }
+}
@@ -0,0 +1,113 @@
+//! Versioned layouts of stored records and contract state
+//!
+//! Records are stored as versioned enums and read as the current layout. A new layout
+//! freezes the current struct as the latest variant and adds a variant for the new one.
+
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::collections::{LookupMap, UnorderedMap};
+use near_sdk::json_types::U128;
+use near_sdk::{AccountId, Balance};
+
+use crate::{Expense, ExpenseId, ParticipantShare, SplitMode, TripId, TripIds, TripMetadata};
+
+// raw storage key holding the state version, v1.0 didn't write it
+pub(crate) const STATE_VERSION_KEY: &[u8] = b"STATE_VERSION";
+// version of the current contract state layout
+pub(crate) const STATE_VERSION: u32 = 2;
+
+// trip metadata as stored by v1.0
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct TripMetadataV1 {
+    pub trip_id: Option<TripId>,
+    pub trip_name: Option<String>,
+    pub trip_members: Option<Vec<AccountId>>,
+}
+
+// expense as stored by v1.0, with a single ower
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct ExpenseV1 {
+    pub expense_id: Option<ExpenseId>,
+    pub expense_name: Option<String>,
+    pub ower_id: AccountId,
+    pub lender_id: AccountId,
+    pub loan_amount: Balance,
+}
+
+// contract state as stored by v1.0
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct ExpenseTrackerV1 {
+    pub trip_id_by_account_id: LookupMap<AccountId,TripIds>,
+    pub trip_metadata_by_trip_id: UnorderedMap<TripId,TripMetadataV1>,
+    pub trip_expenses_by_trip_id: UnorderedMap<TripId,UnorderedMap<ExpenseId,ExpenseV1>>,
+    pub storagekey_counter: u32,
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub enum VersionedTripMetadata {
+    V1(TripMetadataV1),
+    V2(TripMetadata),
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub enum VersionedExpense {
+    V1(ExpenseV1),
+    V2(Expense),
+}
+
+impl From<VersionedTripMetadata> for TripMetadata {
+    fn from(trip_metadata: VersionedTripMetadata) -> Self {
+        match trip_metadata {
+            // v1.0 didn't record the creator, only a trip with a single member has a known owner
+            VersionedTripMetadata::V1(v1) => TripMetadata {
+                trip_id: v1.trip_id,
+                trip_owner: match v1.trip_members.as_deref() {
+                    Some([owner_id]) => Some(owner_id.clone()),
+                    _ => None,
+                },
+                trip_name: v1.trip_name,
+                trip_members: v1.trip_members,
+                trip_admins: Some(vec![]),
+                trip_observers: Some(vec![]),
+                trip_invitees: Some(vec![]),
+            },
+            VersionedTripMetadata::V2(trip_metadata) => trip_metadata,
+        }
+    }
+}
+
+impl From<TripMetadata> for VersionedTripMetadata {
+    fn from(trip_metadata: TripMetadata) -> Self {
+        VersionedTripMetadata::V2(trip_metadata)
+    }
+}
+
+impl From<VersionedExpense> for Expense {
+    fn from(expense: VersionedExpense) -> Self {
+        match expense {
+            // single ower owes the whole loan amount
+            VersionedExpense::V1(v1) => Expense {
+                expense_id: v1.expense_id,
+                expense_name: v1.expense_name,
+                lender_id: v1.lender_id,
+                loan_amount: U128(v1.loan_amount),
+                split_mode: SplitMode::Equal,
+                participant_shares: vec![ParticipantShare {
+                    account_id: v1.ower_id,
+                    share_amount: U128(v1.loan_amount),
+                }],
+            },
+            VersionedExpense::V2(expense) => expense,
+        }
+    }
+}
+
+impl From<Expense> for VersionedExpense {
+    fn from(expense: Expense) -> Self {
+        VersionedExpense::V2(expense)
+    }
+}
+
+// read a collection with another record type, collections only hold their storage prefix
+pub(crate) fn retype_collection<T: BorshSerialize, U: BorshDeserialize>(collection: &T) -> U {
+    U::try_from_slice(&collection.try_to_vec().unwrap()).unwrap()
+}
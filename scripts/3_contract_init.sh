@@ -15,7 +15,7 @@ echo $CONTRACT_ADD
 # init contract
 echo
 echo "contract init"
-near call $CONTRACT_ADD new --accountId $CONTRACT_ADD
+near call $CONTRACT_ADD new '{"owner_id":"'$CONTRACT_ADD'"}' --accountId $CONTRACT_ADD
 
 echo
 echo "!!!REMEMBER TO REPLACE dev-1654439673483-67675783849542 IN SCRIPT 4 WITH ENVIRONMENT VARIABLE'S VALUE IN COMMANDS 5 , 7 , 8 , 9 , 12 , 14 !!!"